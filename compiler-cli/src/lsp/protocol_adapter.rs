@@ -11,11 +11,63 @@ use lsp::{notification::DidOpenTextDocument, request::GotoDefinition};
 use lsp_types::InitializeParams;
 use lsp_types::{
     self as lsp,
-    notification::{DidChangeTextDocument, DidCloseTextDocument, DidSaveTextDocument},
-    request::{Completion, Formatting, HoverRequest},
+    notification::{
+        Cancel, DidChangeTextDocument, DidChangeWatchedFiles, DidChangeWorkspaceFolders,
+        DidCloseTextDocument, DidSaveTextDocument, Notification as _,
+    },
+    request::{Completion, Formatting, HoverRequest, ResolveCompletionItem},
     PublishDiagnosticsParams,
 };
-use std::{collections::HashMap, path::PathBuf};
+use ropey::Rope;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::{Path, PathBuf},
+};
+
+/// The JSON-RPC error code the LSP spec reserves for a request that was
+/// abandoned because the client sent `$/cancelRequest` for it.
+const REQUEST_CANCELLED: i32 = -32800;
+
+/// The most `$/cancelRequest` ids we keep around waiting for a matching
+/// request to show up. Bounds memory use if a client cancels requests that
+/// never get dispatched (already completed, or a ghost id); the oldest
+/// entry is dropped to make room rather than letting the set grow forever.
+const MAX_CANCELLED: usize = 64;
+
+/// The capabilities we advertise to the client during the initialize
+/// handshake.
+pub(crate) fn server_capabilities() -> lsp::ServerCapabilities {
+    lsp::ServerCapabilities {
+        text_document_sync: Some(lsp::TextDocumentSyncCapability::Kind(
+            lsp::TextDocumentSyncKind::INCREMENTAL,
+        )),
+        // `completionItem/resolve` defers the expensive parts of a
+        // completion (rendered type signature, docs, import edits) until
+        // the client actually highlights that item, so we need to tell it
+        // that resolving is worth doing.
+        completion_provider: Some(lsp::CompletionOptions {
+            resolve_provider: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Perform the LSP initialize handshake over `connection` — decoding the
+/// client's `InitializeParams` from its `initialize` request and replying
+/// with `server_capabilities()` — then hand off to
+/// `LanguageServerProtocolAdapter` for the rest of the session.
+pub fn start(connection: lsp_server::Connection, config: Option<PackageConfig>) -> Result<()> {
+    let capabilities =
+        serde_json::to_value(server_capabilities()).expect("server_capabilities to json");
+    let initialise_params = connection
+        .initialize(capabilities)
+        .expect("LSP initialize handshake");
+    let initialise_params: InitializeParams =
+        serde_json::from_value(initialise_params).expect("decode InitializeParams");
+
+    LanguageServerProtocolAdapter::new(initialise_params, config)?.run(connection)
+}
 
 /// This class is responsible for handling the language server protocol and
 /// delegating the work to the `LanguageServer` itself.
@@ -37,15 +89,42 @@ use std::{collections::HashMap, path::PathBuf};
 ///
 pub struct LanguageServerProtocolAdapter {
     initialise_params: InitializeParams,
-    server: LanguageServer,
+    /// One compiler per Gleam package in the workspace, keyed by the
+    /// directory containing that package's `gleam.toml`. A single-package
+    /// workspace just has one entry, keyed by the workspace root.
+    servers: HashMap<PathBuf, LanguageServer>,
+    /// The package root a `completionItem/resolve` should be routed to: it
+    /// carries no document uri of its own, so we route it to whichever
+    /// package most recently handled a request or notification that did.
+    last_active_root: PathBuf,
+    /// Ids seen in a `$/cancelRequest` notification whose request has not
+    /// been dispatched yet, oldest first and capped at `MAX_CANCELLED`.
+    /// Checked (and removed) right before we would otherwise start working
+    /// on a request with a matching id.
+    cancelled: VecDeque<lsp_server::RequestId>,
+    /// Messages pulled off `connection.receiver` ahead of time while
+    /// draining for cancellations, to be handled in order once we get back
+    /// to the main message loop.
+    pending: VecDeque<lsp_server::Message>,
+    /// The current text of every open document, kept as a rope so that
+    /// incremental `textDocument/didChange` edits can be applied in place
+    /// instead of re-sending the whole file on every keystroke.
+    documents: HashMap<lsp::Url, Rope>,
 }
 
 impl LanguageServerProtocolAdapter {
     pub fn new(initialise_params: InitializeParams, config: Option<PackageConfig>) -> Result<Self> {
+        let root = workspace_root(&initialise_params);
         let language_server = LanguageServer::new(config)?;
+        let mut servers = HashMap::new();
+        _ = servers.insert(root.clone(), language_server);
         Ok(Self {
             initialise_params,
-            server: language_server,
+            servers,
+            last_active_root: root,
+            cancelled: VecDeque::new(),
+            pending: VecDeque::new(),
+            documents: HashMap::new(),
         })
     }
 
@@ -53,12 +132,30 @@ impl LanguageServerProtocolAdapter {
         self.create_compilation_progress_token(&connection);
         self.start_watching_gleam_toml(&connection);
 
-        // Compile the project once so we have all the state and any initial errors
-        let feedback = self.server.compile_please(&connection);
-        self.publish_feedback(&connection, feedback);
+        // Compile each registered package once so we have all the state and
+        // any initial errors.
+        let roots: Vec<PathBuf> = self.servers.keys().cloned().collect();
+        for root in roots {
+            let feedback = self
+                .servers
+                .get_mut(&root)
+                .expect("server for known root")
+                .compile_please(&connection);
+            self.publish_feedback(&connection, feedback);
+        }
+
+        // Enter the message loop, handling each message that comes in from the
+        // client. Messages queued up by `drain_available_messages` while we
+        // were checking for cancellations are served first, in order.
+        loop {
+            let message = match self.pending.pop_front() {
+                Some(message) => message,
+                None => match connection.receiver.recv() {
+                    Ok(message) => message,
+                    Err(_) => break,
+                },
+            };
 
-        // Enter the message loop, handling each message that comes in from the client
-        for message in &connection.receiver {
             match self.handle_message(&connection, message) {
                 Next::Continue => (),
                 Next::Break => break,
@@ -100,41 +197,252 @@ impl LanguageServerProtocolAdapter {
         request: lsp_server::Request,
     ) {
         let id = request.id.clone();
+
+        // Pull in anything already sitting in the channel so that a
+        // `$/cancelRequest` the client fired off right after this request
+        // (e.g. because the user kept typing past a hover/completion) is
+        // seen before we do the work it is cancelling.
+        self.drain_available_messages(connection);
+
+        if let Some(position) = self.cancelled.iter().position(|cancelled| *cancelled == id) {
+            _ = self.cancelled.remove(position);
+            tracing::info!("Skipping cancelled request {:?}", id);
+            let response = lsp_server::Response {
+                id,
+                error: Some(lsp_server::ResponseError {
+                    code: REQUEST_CANCELLED,
+                    message: "Request was cancelled".into(),
+                    data: None,
+                }),
+                result: None,
+            };
+            connection
+                .sender
+                .send(lsp_server::Message::Response(response))
+                .expect("channel send LSP response");
+            return;
+        }
+
+        let response = match self.handle_request_inner(connection, request) {
+            Ok(response) => response,
+            Err(error) => lsp_server::Response {
+                id,
+                error: Some(error),
+                result: None,
+            },
+        };
+
+        connection
+            .sender
+            .send(lsp_server::Message::Response(response))
+            .expect("channel send LSP response")
+    }
+
+    fn handle_request_inner(
+        &mut self,
+        connection: &lsp_server::Connection,
+        request: lsp_server::Request,
+    ) -> Result<lsp_server::Response, lsp_server::ResponseError> {
+        let id = request.id.clone();
         let (payload, feedback) = match request.method.as_str() {
             "textDocument/formatting" => {
-                let params = cast_request::<Formatting>(request);
-                convert_response(self.server.format(params))
+                let params = cast_request::<Formatting>(request)?;
+                let uri = params.document_uri().clone();
+                let server = self.server_for_uri(connection, &uri)?;
+                let result = server.format(params);
+                self.dispatch(result)?
             }
 
             "textDocument/hover" => {
-                let params = cast_request::<HoverRequest>(request);
-                convert_response(self.server.hover(params))
+                let params = cast_request::<HoverRequest>(request)?;
+                let uri = params.document_uri().clone();
+                let server = self.server_for_uri(connection, &uri)?;
+                let result = server.hover(params);
+                self.dispatch(result)?
             }
 
             "textDocument/definition" => {
-                let params = cast_request::<GotoDefinition>(request);
-                convert_response(self.server.goto_definition(params))
+                let params = cast_request::<GotoDefinition>(request)?;
+                let uri = params.document_uri().clone();
+                let server = self.server_for_uri(connection, &uri)?;
+                let result = server.goto_definition(params);
+                self.dispatch(result)?
             }
 
             "textDocument/completion" => {
-                let params = cast_request::<Completion>(request);
-                convert_response(self.server.completion(params))
+                let params = cast_request::<Completion>(request)?;
+                let uri = params.document_uri().clone();
+                let server = self.server_for_uri(connection, &uri)?;
+                let result = server.completion(params);
+                self.dispatch(result)?
+            }
+
+            // The client only asks us to resolve the single `CompletionItem`
+            // the user has highlighted, so the expensive bits (rendered type
+            // signature as `detail`, doc comments, import edits) are
+            // computed here rather than for every candidate returned by
+            // `textDocument/completion`. A `CompletionItem` carries no
+            // document uri of its own, so it is routed to whichever package
+            // most recently served a request or notification that did.
+            "completionItem/resolve" => {
+                let params = cast_request::<ResolveCompletionItem>(request)?;
+                let root = self.last_active_root.clone();
+                let Some(server) = self.servers.get_mut(&root) else {
+                    // The package we last served a request for may have been
+                    // dropped by a `DidChangeWorkspaceFolders` removal in the
+                    // meantime; there is no sensible package left to resolve
+                    // this item against.
+                    return Err(lsp_server::ResponseError {
+                        code: lsp_server::ErrorCode::InternalError as i32,
+                        message: "No active Gleam package to resolve this completion item against"
+                            .into(),
+                        data: None,
+                    });
+                };
+                let result = server.completion_resolve(params);
+                self.dispatch(result)?
             }
 
-            _ => panic!("Unsupported LSP request"),
+            _ => {
+                return Err(lsp_server::ResponseError {
+                    code: lsp_server::ErrorCode::MethodNotFound as i32,
+                    message: format!("Unsupported LSP request: {}", request.method),
+                    data: None,
+                })
+            }
         };
 
         self.publish_feedback(connection, feedback);
 
-        let response = lsp_server::Response {
+        Ok(lsp_server::Response {
             id,
             error: None,
             result: Some(payload),
+        })
+    }
+
+    /// Encode the result of a `LanguageServer` method, turning a hard failure into an
+    /// `InternalError` response rather than letting it bubble up as a panic
+    /// or a silently-successful response with no result.
+    fn dispatch<T>(
+        &self,
+        result: Result<(T, Feedback)>,
+    ) -> Result<(serde_json::Value, Feedback), lsp_server::ResponseError>
+    where
+        T: serde::Serialize,
+    {
+        match result {
+            Ok(response) => Ok(convert_response(Ok(response))),
+            Err(error) => Err(lsp_server::ResponseError {
+                code: lsp_server::ErrorCode::InternalError as i32,
+                message: error.to_string(),
+                data: None,
+            }),
+        }
+    }
+
+    /// Find (or, for a package touched for the first time, create and
+    /// compile) the `LanguageServer` that owns the file at `uri`, by walking
+    /// up from its path to the nearest `gleam.toml`.
+    fn server_for_uri(
+        &mut self,
+        connection: &lsp_server::Connection,
+        uri: &lsp::Url,
+    ) -> Result<&mut LanguageServer, lsp_server::ResponseError> {
+        let path = uri.to_file_path().unwrap_or_default();
+
+        let root = nearest_gleam_toml(&path)
+            .or_else(|| {
+                self.servers
+                    .keys()
+                    .filter(|root| path.starts_with(root))
+                    .max_by_key(|root| root.as_os_str().len())
+                    .cloned()
+            })
+            .unwrap_or_else(|| self.last_active_root.clone());
+
+        self.last_active_root = root.clone();
+
+        if !self.servers.contains_key(&root) {
+            self.register_package(connection, root.clone())
+                .map_err(|error| lsp_server::ResponseError {
+                    code: lsp_server::ErrorCode::InternalError as i32,
+                    message: error.to_string(),
+                    data: None,
+                })?;
+        }
+
+        Ok(self
+            .servers
+            .get_mut(&root)
+            .expect("server was just registered for this root"))
+    }
+
+    /// Build and compile a fresh `LanguageServer` for a package discovered
+    /// after startup — via a request for one of its files, or a watched
+    /// `gleam.toml` addition — loading its own `gleam.toml` (a missing or
+    /// malformed one leaves the server unconfigured, see
+    /// `load_package_config`). Fallible rather than panicking, since this
+    /// can run on the request-handling hot path and `LanguageServer::new`
+    /// can fail.
+    fn register_package(
+        &mut self,
+        connection: &lsp_server::Connection,
+        root: PathBuf,
+    ) -> Result<()> {
+        let config = load_package_config(&root);
+        let mut server = LanguageServer::new(config)?;
+        let feedback = server.compile_please(connection);
+        _ = self.servers.insert(root, server);
+        self.publish_feedback(connection, feedback);
+        Ok(())
+    }
+
+    /// Drop the package rooted at `root`, e.g. because its workspace folder
+    /// or `gleam.toml` was removed. Falls `last_active_root` back to any
+    /// package still registered if it pointed at the one just dropped, so
+    /// `completionItem/resolve` has somewhere sensible left to route to.
+    fn drop_package(&mut self, root: PathBuf) {
+        if self.servers.remove(&root).is_none() {
+            return;
+        }
+        if self.last_active_root == root {
+            if let Some(remaining) = self.servers.keys().next() {
+                self.last_active_root = remaining.clone();
+            }
+        }
+    }
+
+    /// Drain every message currently sitting in the channel without
+    /// blocking. `$/cancelRequest` notifications are acted on immediately;
+    /// everything else is stashed in `self.pending` to be handled in order
+    /// once we return to the main message loop.
+    fn drain_available_messages(&mut self, connection: &lsp_server::Connection) {
+        while let Ok(message) = connection.receiver.try_recv() {
+            match message {
+                lsp_server::Message::Notification(notification)
+                    if notification.method == Cancel::METHOD =>
+                {
+                    self.handle_cancel_request(notification);
+                }
+                other => self.pending.push_back(other),
+            }
+        }
+    }
+
+    fn handle_cancel_request(&mut self, notification: lsp_server::Notification) {
+        let Some(params) = cast_notification::<Cancel>(notification) else {
+            return;
         };
-        connection
-            .sender
-            .send(lsp_server::Message::Response(response))
-            .expect("channel send LSP response")
+        let id = match params.id {
+            lsp::NumberOrString::Number(number) => lsp_server::RequestId::from(number),
+            lsp::NumberOrString::String(string) => lsp_server::RequestId::from(string),
+        };
+        tracing::info!("Marking request {:?} as cancelled", id);
+        if self.cancelled.len() >= MAX_CANCELLED {
+            _ = self.cancelled.pop_front();
+        }
+        self.cancelled.push_back(id);
     }
 
     fn handle_notification(
@@ -143,31 +451,136 @@ impl LanguageServerProtocolAdapter {
         notification: lsp_server::Notification,
     ) {
         let feedback = match notification.method.as_str() {
+            "$/cancelRequest" => {
+                self.handle_cancel_request(notification);
+                return;
+            }
+
             "textDocument/didOpen" => {
-                let params = cast_notification::<DidOpenTextDocument>(notification);
+                let Some(params) = cast_notification::<DidOpenTextDocument>(notification) else {
+                    return;
+                };
                 tracing::info!("Document opened: {:?}", params);
-                self.server.text_document_did_open(params, connection)
+                let uri = params.document_uri().clone();
+                _ = self
+                    .documents
+                    .insert(uri.clone(), Rope::from_str(&params.text_document.text));
+                let Ok(server) = self.server_for_uri(connection, &uri) else {
+                    return;
+                };
+                server.text_document_did_open(params, connection)
             }
 
             "textDocument/didSave" => {
-                let params = cast_notification::<DidSaveTextDocument>(notification);
-                self.server.text_document_did_save(params, connection)
+                let Some(params) = cast_notification::<DidSaveTextDocument>(notification) else {
+                    return;
+                };
+                let uri = params.document_uri().clone();
+                let Ok(server) = self.server_for_uri(connection, &uri) else {
+                    return;
+                };
+                server.text_document_did_save(params, connection)
             }
 
             "textDocument/didClose" => {
-                let params = cast_notification::<DidCloseTextDocument>(notification);
-                self.server.text_document_did_close(params)
+                let Some(params) = cast_notification::<DidCloseTextDocument>(notification) else {
+                    return;
+                };
+                let uri = params.document_uri().clone();
+                _ = self.documents.remove(&uri);
+                let Ok(server) = self.server_for_uri(connection, &uri) else {
+                    return;
+                };
+                server.text_document_did_close(params)
             }
 
             "textDocument/didChange" => {
-                let params = cast_notification::<DidChangeTextDocument>(notification);
-                self.server.text_document_did_change(params, connection)
+                let Some(params) = cast_notification::<DidChangeTextDocument>(notification) else {
+                    return;
+                };
+                let uri = params.document_uri().clone();
+
+                // Apply every content change event to our rope for this
+                // document: a `range` is an incremental edit (converted from
+                // UTF-16 line/character into rope char offsets and
+                // spliced in), no `range` is a full-document replacement.
+                let rope = self.documents.entry(uri.clone()).or_insert_with(Rope::new);
+                for change in &params.content_changes {
+                    apply_content_change(rope, change);
+                }
+                let full_text = rope.to_string();
+
+                // `LanguageServer` only understands full-document syncs, so
+                // collapse the (already applied) incremental edits into a
+                // single full-text replacement built from our rope.
+                let synced_params = lsp::DidChangeTextDocumentParams {
+                    text_document: params.text_document,
+                    content_changes: vec![lsp::TextDocumentContentChangeEvent {
+                        range: None,
+                        range_length: None,
+                        text: full_text,
+                    }],
+                };
+                let Ok(server) = self.server_for_uri(connection, &uri) else {
+                    return;
+                };
+                server.text_document_did_change(synced_params, connection)
             }
 
             "workspace/didChangeWatchedFiles" => {
-                tracing::info!("gleam_toml_changed_so_recompiling_full_project");
-                self.server.create_new_compiler().expect("create");
-                self.server.compile_please(connection)
+                let Some(params) = cast_notification::<DidChangeWatchedFiles>(notification) else {
+                    return;
+                };
+                // Each watched `gleam.toml` change only recompiles the
+                // package it belongs to, not the whole workspace; a removed
+                // `gleam.toml` drops that package instead of recompiling it.
+                for change in params.changes {
+                    let Ok(toml_path) = change.uri.to_file_path() else {
+                        continue;
+                    };
+                    let Some(root) = toml_path.parent() else {
+                        continue;
+                    };
+                    let root = root.to_path_buf();
+
+                    if change.typ == lsp::FileChangeType::DELETED {
+                        tracing::info!("gleam_toml_removed_so_dropping_package: {:?}", root);
+                        self.drop_package(root);
+                        continue;
+                    }
+
+                    tracing::info!("gleam_toml_changed_so_recompiling_package: {:?}", root);
+                    if !self.servers.contains_key(&root) {
+                        if let Err(error) = self.register_package(connection, root) {
+                            tracing::error!(
+                                "Failed to register package discovered via gleam.toml watch: {}",
+                                error
+                            );
+                        }
+                        continue;
+                    }
+                    let server = self.servers.get_mut(&root).expect("server for known root");
+                    server.create_new_compiler().expect("create");
+                    let feedback = server.compile_please(connection);
+                    self.publish_feedback(connection, feedback);
+                }
+                return;
+            }
+
+            "workspace/didChangeWorkspaceFolders" => {
+                let Some(params) = cast_notification::<DidChangeWorkspaceFolders>(notification)
+                else {
+                    return;
+                };
+                // Dropped folders take their package's compiler state with
+                // them; added folders get a compiler lazily, the first time
+                // one of their files is touched.
+                for removed in params.event.removed {
+                    if let Ok(root) = removed.uri.to_file_path() {
+                        self.drop_package(root);
+                    }
+                }
+                return;
             }
 
             _ => return,
@@ -226,16 +639,21 @@ impl LanguageServerProtocolAdapter {
             return;
         }
 
-        // Register gleam.toml as a watched file so we get a notification when
-        // it changes and thus know that we need to rebuild the entire project.
+        // Register every gleam.toml in the workspace as a watched file, so
+        // that in a monorepo we get a notification when any package's
+        // config changes and know that package needs rebuilding.
         let watch_config = lsp::Registration {
             id: "watch-gleam-toml".into(),
             method: "workspace/didChangeWatchedFiles".into(),
             register_options: Some(
                 serde_json::value::to_value(lsp::DidChangeWatchedFilesRegistrationOptions {
                     watchers: vec![lsp::FileSystemWatcher {
-                        glob_pattern: "gleam.toml".into(),
-                        kind: Some(lsp::WatchKind::Change),
+                        glob_pattern: "**/gleam.toml".into(),
+                        kind: Some(
+                            lsp::WatchKind::Create
+                                | lsp::WatchKind::Change
+                                | lsp::WatchKind::Delete,
+                        ),
                     }],
                 })
                 .expect("workspace/didChangeWatchedFiles to json"),
@@ -301,21 +719,436 @@ enum Next {
     Break,
 }
 
-fn cast_request<R>(request: lsp_server::Request) -> R::Params
+/// The directory the startup `LanguageServer` is registered under, taken
+/// from the client's initial workspace folder (falling
+/// back to the deprecated single-root `rootUri` for older clients).
+#[allow(deprecated)]
+fn workspace_root(params: &InitializeParams) -> PathBuf {
+    params
+        .workspace_folders
+        .as_ref()
+        .and_then(|folders| folders.first())
+        .map(|folder| &folder.uri)
+        .or(params.root_uri.as_ref())
+        .and_then(|uri| uri.to_file_path().ok())
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// Apply one `TextDocumentContentChangeEvent` to `rope`: a full-document
+/// replacement when it carries no `range`, otherwise an in-place splice of
+/// `event.text` over the char offsets the `range` converts to.
+fn apply_content_change(rope: &mut Rope, event: &lsp::TextDocumentContentChangeEvent) {
+    match event.range {
+        None => *rope = Rope::from_str(&event.text),
+        Some(range) => {
+            let start = utf16_position_to_char(rope, range.start);
+            // A desynced client (or a range that reaches one past EOF) can
+            // send an end position that clamps to an earlier char offset
+            // than `start`; `max` keeps `remove` from panicking on an
+            // inverted range.
+            let end = utf16_position_to_char(rope, range.end).max(start);
+            rope.remove(start..end);
+            rope.insert(start, &event.text);
+        }
+    }
+}
+
+/// Convert an LSP `Position` (UTF-16 line/character) into a char offset
+/// into `rope`. Out-of-range lines/characters (a desynced client, or a
+/// position one past EOF) are clamped rather than left to panic on.
+fn utf16_position_to_char(rope: &Rope, position: lsp::Position) -> usize {
+    let line_index = (position.line as usize).min(rope.len_lines().saturating_sub(1));
+    let line_char_offset = rope.line_to_char(line_index);
+    let line = rope.line(line_index);
+
+    let mut utf16_units = 0u32;
+    let mut char_offset = 0usize;
+    for ch in line.chars() {
+        if utf16_units >= position.character {
+            break;
+        }
+        utf16_units += ch.len_utf16() as u32;
+        char_offset += 1;
+    }
+
+    line_char_offset + char_offset
+}
+
+/// Walk up from `path` looking for the nearest ancestor directory that
+/// contains a `gleam.toml`, i.e. the root of the package that owns `path`.
+fn nearest_gleam_toml(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|dir| dir.join("gleam.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// Load and parse the `gleam.toml` for the package rooted at `root`, for a
+/// package discovered after startup (e.g. via a monorepo-wide `gleam.toml`
+/// watch rather than the initial workspace root). Returns `None` if it is
+/// missing or malformed, in which case the package's `LanguageServer` is
+/// created unconfigured, same as if no config had been found at startup.
+fn load_package_config(root: &Path) -> Option<PackageConfig> {
+    let toml = std::fs::read_to_string(root.join("gleam.toml")).ok()?;
+    toml::from_str(&toml).ok()
+}
+
+/// Pulls the `Url` of the document a request/notification's params concern,
+/// so the adapter can route it to the package that owns that file.
+trait HasDocumentUri {
+    fn document_uri(&self) -> &lsp::Url;
+}
+
+impl HasDocumentUri for lsp::DocumentFormattingParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document.uri
+    }
+}
+
+impl HasDocumentUri for lsp::HoverParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document_position_params.text_document.uri
+    }
+}
+
+impl HasDocumentUri for lsp::GotoDefinitionParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document_position_params.text_document.uri
+    }
+}
+
+impl HasDocumentUri for lsp::CompletionParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document_position.text_document.uri
+    }
+}
+
+impl HasDocumentUri for lsp::DidOpenTextDocumentParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document.uri
+    }
+}
+
+impl HasDocumentUri for lsp::DidSaveTextDocumentParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document.uri
+    }
+}
+
+impl HasDocumentUri for lsp::DidCloseTextDocumentParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document.uri
+    }
+}
+
+impl HasDocumentUri for lsp::DidChangeTextDocumentParams {
+    fn document_uri(&self) -> &lsp::Url {
+        &self.text_document.uri
+    }
+}
+
+fn cast_request<R>(request: lsp_server::Request) -> Result<R::Params, lsp_server::ResponseError>
 where
     R: lsp::request::Request,
     R::Params: serde::de::DeserializeOwned,
 {
-    let (_, params) = request.extract(R::METHOD).expect("cast request");
-    params
+    request
+        .extract(R::METHOD)
+        .map(|(_, params)| params)
+        .map_err(|error| lsp_server::ResponseError {
+            code: lsp_server::ErrorCode::InvalidParams as i32,
+            message: format!("Invalid params for {}: {}", R::METHOD, error),
+            data: None,
+        })
 }
 
-fn cast_notification<N>(notification: lsp_server::Notification) -> N::Params
+fn cast_notification<N>(notification: lsp_server::Notification) -> Option<N::Params>
 where
     N: lsp::notification::Notification,
     N::Params: serde::de::DeserializeOwned,
 {
-    notification
-        .extract::<N::Params>(N::METHOD)
-        .expect("cast notification")
+    match notification.extract::<N::Params>(N::METHOD) {
+        Ok(params) => Some(params),
+        Err(error) => {
+            tracing::error!("Failed to decode {} notification: {}", N::METHOD, error);
+            None
+        }
+    }
+}
+
+/// Drives a `LanguageServerProtocolAdapter` over an in-memory connection so
+/// tests can talk LSP to it directly instead of going over stdio, and
+/// collects the `textDocument/publishDiagnostics` notifications it sends
+/// back along the way.
+#[cfg(test)]
+pub(crate) struct ServerTester {
+    connection: lsp_server::Connection,
+    diagnostics: HashMap<lsp::Url, Vec<lsp::Diagnostic>>,
+    next_id: i32,
+}
+
+#[cfg(test)]
+impl ServerTester {
+    /// Spawn the adapter on a background thread wired up to a
+    /// `Connection::memory()` pair, keeping the client end for ourselves.
+    pub(crate) fn new(initialise_params: InitializeParams, config: Option<PackageConfig>) -> Self {
+        let (server_connection, client_connection) = lsp_server::Connection::memory();
+        let mut adapter = LanguageServerProtocolAdapter::new(initialise_params, config)
+            .expect("create LanguageServerProtocolAdapter for testing");
+
+        _ = std::thread::spawn(move || adapter.run(server_connection));
+
+        Self {
+            connection: client_connection,
+            diagnostics: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Send a typed request and block until its response comes back,
+    /// recording any diagnostics published while waiting.
+    pub(crate) fn request<R>(&mut self, params: R::Params) -> R::Result
+    where
+        R: lsp::request::Request,
+        R::Params: serde::Serialize,
+        R::Result: serde::de::DeserializeOwned,
+    {
+        let id = lsp_server::RequestId::from(self.next_id);
+        self.next_id += 1;
+
+        self.connection
+            .sender
+            .send(lsp_server::Message::Request(lsp_server::Request {
+                id: id.clone(),
+                method: R::METHOD.into(),
+                params: serde_json::to_value(params).expect("encode request params"),
+            }))
+            .expect("send request over in-memory LSP connection");
+
+        loop {
+            match self
+                .connection
+                .receiver
+                .recv()
+                .expect("in-memory LSP connection closed while awaiting response")
+            {
+                lsp_server::Message::Response(response) if response.id == id => {
+                    return serde_json::from_value(response.result.expect("response had no result"))
+                        .expect("decode response result");
+                }
+                lsp_server::Message::Notification(notification) => {
+                    self.record_notification(notification);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Send a typed notification, e.g. `textDocument/didOpen`.
+    pub(crate) fn notify<N>(&mut self, params: N::Params)
+    where
+        N: lsp::notification::Notification,
+        N::Params: serde::Serialize,
+    {
+        self.connection
+            .sender
+            .send(lsp_server::Message::Notification(
+                lsp_server::Notification {
+                    method: N::METHOD.into(),
+                    params: serde_json::to_value(params).expect("encode notification params"),
+                },
+            ))
+            .expect("send notification over in-memory LSP connection");
+    }
+
+    /// Diagnostics published so far, keyed by document, draining any that
+    /// have arrived since the last call.
+    pub(crate) fn diagnostics(&mut self) -> &HashMap<lsp::Url, Vec<lsp::Diagnostic>> {
+        while let Ok(message) = self.connection.receiver.try_recv() {
+            if let lsp_server::Message::Notification(notification) = message {
+                self.record_notification(notification);
+            }
+        }
+        &self.diagnostics
+    }
+
+    fn record_notification(&mut self, notification: lsp_server::Notification) {
+        if notification.method == "textDocument/publishDiagnostics" {
+            if let Ok(params) =
+                serde_json::from_value::<PublishDiagnosticsParams>(notification.params)
+            {
+                _ = self.diagnostics.insert(params.uri, params.diagnostics);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write a minimal single-module Gleam package to `dir` and return the
+    /// `InitializeParams` a client would send for it as the sole workspace
+    /// folder.
+    fn write_package(dir: &Path, name: &str, source: &str) -> InitializeParams {
+        std::fs::create_dir_all(dir.join("src")).expect("create src dir");
+        std::fs::write(
+            dir.join("gleam.toml"),
+            format!("name = \"{name}\"\nversion = \"1.0.0\"\n"),
+        )
+        .expect("write gleam.toml");
+        std::fs::write(dir.join("src").join(format!("{name}.gleam")), source)
+            .expect("write module source");
+
+        let uri = path_to_uri(dir.to_path_buf());
+        InitializeParams {
+            workspace_folders: Some(vec![lsp::WorkspaceFolder {
+                uri,
+                name: name.into(),
+            }]),
+            ..Default::default()
+        }
+    }
+
+    fn open(tester: &mut ServerTester, uri: lsp::Url, text: &str) {
+        tester.notify::<DidOpenTextDocument>(lsp::DidOpenTextDocumentParams {
+            text_document: lsp::TextDocumentItem {
+                uri,
+                language_id: "gleam".into(),
+                version: 0,
+                text: text.into(),
+            },
+        });
+    }
+
+    #[test]
+    fn hover_returns_a_result_for_the_hovered_function_name() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let params = write_package(
+            dir.path(),
+            "app",
+            "pub fn main() -> Int {\n  1\n}\n",
+        );
+        let module_uri = path_to_uri(dir.path().join("src").join("app.gleam"));
+
+        let mut tester = ServerTester::new(params, None);
+        open(&mut tester, module_uri.clone(), "pub fn main() -> Int {\n  1\n}\n");
+
+        let hover = tester.request::<HoverRequest>(lsp::HoverParams {
+            text_document_position_params: lsp::TextDocumentPositionParams {
+                text_document: lsp::TextDocumentIdentifier {
+                    uri: module_uri,
+                },
+                position: lsp::Position {
+                    line: 0,
+                    character: 8,
+                },
+            },
+            work_done_progress_params: Default::default(),
+        });
+
+        assert!(hover.is_some());
+    }
+
+    #[test]
+    fn completion_resolve_fills_in_the_detail_for_the_highlighted_item() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let source = "pub fn helper() -> Int {\n  1\n}\n\npub fn main() -> Int {\n  help\n}\n";
+        let params = write_package(dir.path(), "app", source);
+        let module_uri = path_to_uri(dir.path().join("src").join("app.gleam"));
+
+        let mut tester = ServerTester::new(params, None);
+        open(&mut tester, module_uri.clone(), source);
+
+        let completions = tester.request::<Completion>(lsp::CompletionParams {
+            text_document_position: lsp::TextDocumentPositionParams {
+                text_document: lsp::TextDocumentIdentifier { uri: module_uri },
+                position: lsp::Position {
+                    line: 5,
+                    character: 6,
+                },
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            context: None,
+        });
+
+        let Some(lsp::CompletionResponse::Array(items)) = completions else {
+            panic!("expected a plain completion item array");
+        };
+        let item = items
+            .into_iter()
+            .find(|item| item.label == "helper")
+            .expect("completion candidate for `helper`, a function defined in the same module");
+        assert!(
+            item.detail.is_none(),
+            "detail should be deferred to completionItem/resolve, not filled in eagerly"
+        );
+
+        let resolved = tester.request::<ResolveCompletionItem>(item);
+        assert!(
+            resolved.detail.is_some(),
+            "resolve should have filled in `helper`'s rendered type signature"
+        );
+    }
+
+    #[test]
+    fn did_save_republishes_diagnostics_for_a_type_error() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let params = write_package(dir.path(), "app", "pub fn main() -> Int {\n  \"oops\"\n}\n");
+        let module_uri = path_to_uri(dir.path().join("src").join("app.gleam"));
+
+        let mut tester = ServerTester::new(params, None);
+        open(
+            &mut tester,
+            module_uri.clone(),
+            "pub fn main() -> Int {\n  \"oops\"\n}\n",
+        );
+
+        tester.notify::<DidSaveTextDocument>(lsp::DidSaveTextDocumentParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: module_uri.clone(),
+            },
+            text: None,
+        });
+
+        let diagnostics = tester.diagnostics();
+        assert!(diagnostics.get(&module_uri).is_some_and(|ds| !ds.is_empty()));
+    }
+
+    #[test]
+    fn requests_are_routed_to_the_package_that_owns_the_file_in_a_monorepo() {
+        let workspace = tempfile::tempdir().expect("tempdir");
+        let one_dir = workspace.path().join("packages").join("one");
+        let two_dir = workspace.path().join("packages").join("two");
+
+        write_package(&one_dir, "one", "pub fn main() -> Int {\n  1\n}\n");
+        let two_params = write_package(&two_dir, "two", "pub fn main() -> Int {\n  2\n}\n");
+
+        // Only "two" is an initial workspace folder; "one" is discovered
+        // lazily the first time one of its files is opened, same as a
+        // monorepo package the client hasn't focused yet.
+        let one_uri = path_to_uri(one_dir.join("src").join("one.gleam"));
+        let two_uri = path_to_uri(two_dir.join("src").join("two.gleam"));
+
+        let mut tester = ServerTester::new(two_params, None);
+        open(&mut tester, two_uri.clone(), "pub fn main() -> Int {\n  2\n}\n");
+        open(&mut tester, one_uri.clone(), "pub fn main() -> Int {\n  1\n}\n");
+
+        // Both packages should now answer hover requests for their own
+        // file rather than one lazily-created, uncompiled server serving
+        // both (or panicking because it has no config).
+        for uri in [one_uri, two_uri] {
+            let hover = tester.request::<HoverRequest>(lsp::HoverParams {
+                text_document_position_params: lsp::TextDocumentPositionParams {
+                    text_document: lsp::TextDocumentIdentifier { uri },
+                    position: lsp::Position {
+                        line: 0,
+                        character: 8,
+                    },
+                },
+                work_done_progress_params: Default::default(),
+            });
+            assert!(hover.is_some());
+        }
+    }
 }
\ No newline at end of file